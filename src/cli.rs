@@ -0,0 +1,49 @@
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use redis::aio::PubSub;
+
+/// Which source of truth to use when determining a subreddit's current
+/// access state.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum StateBackend {
+    #[default]
+    Scraper,
+    Roux,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct Cli {
+    #[arg(long, env = "REDDARK_REDIS_URL", default_value = "redis://127.0.0.1/")]
+    pub redis_url: String,
+
+    #[arg(long, default_value_t = 16)]
+    pub redis_pool_size: usize,
+
+    #[arg(long = "discord-webhook", alias = "notify-webhook")]
+    pub notify_webhooks: Vec<String>,
+
+    #[arg(long, default_value_t = 1024)]
+    pub delta_stream_buffer_size: usize,
+
+    #[arg(long, value_enum, default_value_t = StateBackend::Scraper)]
+    pub state_backend: StateBackend,
+
+    #[arg(long)]
+    pub roux_client_id: Option<String>,
+
+    #[arg(long)]
+    pub roux_client_secret: Option<String>,
+
+    #[arg(long)]
+    pub roux_username: Option<String>,
+
+    #[arg(long)]
+    pub roux_password: Option<String>,
+}
+
+impl Cli {
+    pub async fn new_redis_pubsub(&self) -> Result<PubSub> {
+        let client = redis::Client::open(self.redis_url.as_str())?;
+        Ok(client.get_async_connection().await?.into_pubsub())
+    }
+}
@@ -0,0 +1,105 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_gauge_vec, Encoder, Histogram,
+    IntCounter, IntGaugeVec, TextEncoder,
+};
+use tracing::{error, info};
+use warp::Filter;
+
+use crate::redis_helper::RedisHelper;
+use crate::reddit::SubredditState;
+
+pub static STATE_GAUGE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "reddark_subreddit_state",
+        "Number of subreddits currently in a given state, by section bucket",
+        &["state", "section"]
+    )
+    .unwrap()
+});
+
+pub static DELTAS_SENT: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "reddark_deltas_sent_total",
+        "Total number of subreddit deltas published"
+    )
+    .unwrap()
+});
+
+pub static DELTAS_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "reddark_deltas_skipped_total",
+        "Total number of subreddit deltas skipped"
+    )
+    .unwrap()
+});
+
+pub static LIMITER_WAIT_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "reddark_limiter_wait_seconds",
+        "Time spent waiting on the send_delta rate limiter"
+    )
+    .unwrap()
+});
+
+pub static DELTAS_STREAM_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "reddark_delta_stream_dropped_total",
+        "Total number of delta stream messages dropped due to a saturated consumer buffer"
+    )
+    .unwrap()
+});
+
+// Recomputed on every scrape rather than updated incrementally, so it can
+// never drift from what get_current_state actually reports. The reset only
+// happens once get_current_state has actually succeeded, so a transient
+// Redis error leaves the last-known-good gauge values in place instead of
+// zeroing every label.
+async fn refresh_state_gauge(redis: &RedisHelper) -> Result<()> {
+    let subreddits = redis.get_current_state().await?;
+    STATE_GAUGE.reset();
+    for subreddit in subreddits {
+        let state_label = match subreddit.state {
+            SubredditState::PRIVATE => "PRIVATE",
+            SubredditState::PUBLIC => "PUBLIC",
+            SubredditState::RESTRICTED => "RESTRICTED",
+            SubredditState::UNKNOWN => "UNKNOWN",
+        };
+        STATE_GAUGE
+            .with_label_values(&[state_label, &subreddit.section])
+            .inc();
+    }
+    Ok(())
+}
+
+async fn render_metrics(redis: RedisHelper) -> Result<String, Infallible> {
+    if let Err(err) = refresh_state_gauge(&redis).await {
+        error!("Failed to refresh subreddit state gauges: {err}");
+    }
+
+    let metric_families = prometheus::gather();
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    encoder.encode(&metric_families, &mut buffer).unwrap();
+    Ok(String::from_utf8(buffer).unwrap())
+}
+
+pub async fn serve_metrics(addr: SocketAddr, redis: RedisHelper) -> Result<()> {
+    let route = warp::path("metrics").and_then(move || {
+        let redis = redis.clone();
+        async move { render_metrics(redis).await }
+    });
+
+    info!("Serving Prometheus metrics on {addr}/metrics");
+    warp::serve(route).run(addr).await;
+    Ok(())
+}
+
+pub fn observe_limiter_wait(wait: Duration) {
+    LIMITER_WAIT_SECONDS.observe(wait.as_secs_f64());
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use governor::middleware::NoOpMiddleware;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{clock, Quota, RateLimiter};
+use nonzero_ext::nonzero;
+use tokio::sync::Mutex;
+use tokio::time::interval;
+use tracing::{error, warn};
+
+use crate::reddit::SubredditState;
+
+#[derive(Clone, Debug)]
+pub struct StateTransition {
+    pub subreddit: String,
+    pub prev_state: SubredditState,
+    pub new_state: SubredditState,
+    pub section: String,
+}
+
+struct WebhookTarget {
+    url: String,
+    limiter: RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock, NoOpMiddleware>,
+    pending: Mutex<Vec<StateTransition>>,
+}
+
+// Buffers transitions per-webhook, flushed on a short interval so a burst
+// during a blackout doesn't trip the webhook's own rate limit.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    targets: Vec<Arc<WebhookTarget>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(webhook_urls: Vec<String>, batch_window: Duration) -> Arc<Self> {
+        let targets: Vec<Arc<WebhookTarget>> = dedup(webhook_urls)
+            .into_iter()
+            .map(|url| {
+                Arc::new(WebhookTarget {
+                    url,
+                    // Discord/Slack both tolerate roughly one message per second per webhook.
+                    limiter: RateLimiter::direct(Quota::per_second(nonzero!(1u32))),
+                    pending: Mutex::new(Vec::new()),
+                })
+            })
+            .collect();
+
+        let notifier = Arc::new(Self {
+            client: reqwest::Client::new(),
+            targets,
+        });
+
+        notifier.clone().spawn_flush_loop(batch_window);
+        notifier
+    }
+
+    fn spawn_flush_loop(self: Arc<Self>, batch_window: Duration) {
+        for target in self.targets.clone() {
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                let mut ticker = interval(batch_window);
+                loop {
+                    ticker.tick().await;
+                    let batch = {
+                        let mut pending = target.pending.lock().await;
+                        if pending.is_empty() {
+                            continue;
+                        }
+                        std::mem::take(&mut *pending)
+                    };
+                    target.limiter.until_ready().await;
+                    if let Err(err) = Self::post_batch(&client, &target.url, &batch).await {
+                        error!(
+                            "Failed to post webhook notification to {}: {err}, re-queueing {} transition(s)",
+                            target.url,
+                            batch.len()
+                        );
+                        // Don't lose transitions to a webhook outage; retry them on the next tick.
+                        let mut pending = target.pending.lock().await;
+                        let mut requeued = batch;
+                        requeued.append(&mut pending);
+                        *pending = requeued;
+                    }
+                }
+            });
+        }
+    }
+
+    pub async fn notify(&self, transition: StateTransition) {
+        for target in &self.targets {
+            target.pending.lock().await.push(transition.clone());
+        }
+    }
+
+    async fn post_batch(
+        client: &reqwest::Client,
+        url: &str,
+        batch: &[StateTransition],
+    ) -> Result<()> {
+        let lines: Vec<String> = batch
+            .iter()
+            .map(|t| {
+                format!(
+                    "r/{} just went {} ({:?} -> {:?}, {})",
+                    t.subreddit,
+                    format!("{:?}", t.new_state).to_uppercase(),
+                    t.prev_state,
+                    t.new_state,
+                    t.section
+                )
+            })
+            .collect();
+
+        let fields: Vec<serde_json::Value> = batch
+            .iter()
+            .map(|t| {
+                serde_json::json!({
+                    "name": format!(
+                        "r/{} just went {}",
+                        t.subreddit,
+                        format!("{:?}", t.new_state).to_uppercase()
+                    ),
+                    "value": format!(
+                        "{:?} -> {:?} ({})",
+                        t.prev_state, t.new_state, t.section
+                    ),
+                })
+            })
+            .collect();
+
+        // Slack's incoming-webhook endpoint 400s without one of text/blocks/attachments,
+        // so "text" always goes in; Discord renders the richer "embeds" and ignores "text".
+        let body = serde_json::json!({
+            "text": lines.join("\n"),
+            "embeds": [{
+                "title": "reddark subreddit state changes",
+                "fields": fields,
+            }]
+        });
+
+        let resp = client.post(url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            warn!(
+                "Webhook {} responded with non-success status {}",
+                url,
+                resp.status()
+            );
+        }
+        Ok(())
+    }
+}
+
+// `--discord-webhook` can be repeated; collapse duplicates so the same URL
+// doesn't get two independent targets (and a double post per transition).
+fn dedup(urls: Vec<String>) -> Vec<String> {
+    urls.into_iter().collect::<HashMap<_, ()>>().into_keys().collect()
+}
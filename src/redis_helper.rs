@@ -1,37 +1,56 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
-use redis::aio::Connection;
-use tokio::sync::Mutex;
 use anyhow::Result;
+use deadpool_redis::{Config as RedisPoolConfig, Pool, PoolConfig, Runtime};
 use futures_util::TryStream;
 use futures_util::StreamExt;
 use governor::{clock, Jitter, Quota, RateLimiter};
 use governor::middleware::NoOpMiddleware;
 use governor::state::{InMemoryState, NotKeyed};
 use nonzero_ext::nonzero;
-use redis::{AsyncCommands, Msg};
-use tracing::info;
+use redis::{AsyncCommands, Msg, Script};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, warn};
 use crate::Cli;
+use crate::metrics::{observe_limiter_wait, DELTAS_SENT, DELTAS_SKIPPED, DELTAS_STREAM_DROPPED};
+use crate::notifier::{StateTransition, WebhookNotifier};
 use crate::reddit::{Subreddit, SubredditDelta, SubredditState};
 
+/// Starting backoff between pubsub reconnect attempts in `new_delta_stream`,
+/// doubled on each consecutive failure up to `MAX_RECONNECT_BACKOFF`.
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+static APPLY_DELTA_SCRIPT: &str = include_str!("scripts/apply_delta.lua");
+
 #[derive(Clone)]
 pub struct RedisHelper {
-    con: Arc<Mutex<Connection>>,
+    con: Pool,
     limiter: Arc<RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock, NoOpMiddleware>>,
+    notifier: Option<Arc<WebhookNotifier>>,
+    apply_delta_script: Arc<Script>,
 }
 
 impl RedisHelper {
     pub async fn new(cli: &Cli) -> Result<Self> {
-        let con = cli.new_redis_connection().await?;
+        let mut pool_cfg = RedisPoolConfig::from_url(&cli.redis_url);
+        pool_cfg.pool = Some(PoolConfig::new(cli.redis_pool_size));
+        let con = pool_cfg.create_pool(Some(Runtime::Tokio1))?;
+        let notifier = (!cli.notify_webhooks.is_empty()).then(|| {
+            WebhookNotifier::new(cli.notify_webhooks.clone(), Duration::from_secs(5))
+        });
         Ok(Self {
             // Limit the amount of updates a second to 2. Avoids flooding messages.
             limiter: Arc::new(RateLimiter::direct(Quota::per_second(nonzero!(2u32)))),
             con,
+            notifier,
+            apply_delta_script: Arc::new(Script::new(APPLY_DELTA_SCRIPT)),
         })
     }
     pub async fn get_current_state(&self) -> Result<Vec<Subreddit>> {
-        let srs: HashMap<String, String> = self.con.lock().await.hgetall("subreddit").await?;
+        let srs: HashMap<String, String> = self.con.get().await?.hgetall("subreddit").await?;
         let values = srs.values()
             .map(|v| {
                 serde_json::from_str::<Subreddit>(v)
@@ -42,18 +61,18 @@ impl RedisHelper {
 
     pub async fn update_subreddit(&self, subreddit: &Subreddit) -> Result<()> {
         let val = serde_json::to_string(&subreddit)?;
-        self.con.lock().await.hset("subreddit", subreddit.safe_name(), val).await?;
+        self.con.get().await?.hset("subreddit", subreddit.safe_name(), val).await?;
         Ok(())
     }
 
     pub async fn set_sections(&self, sections: Vec<String>) -> Result<()> {
         let val = serde_json::to_string(&sections)?;
-        self.con.lock().await.set("sections", val).await?;
+        self.con.get().await?.set("sections", val).await?;
         Ok(())
     }
 
     pub async fn get_sections(&self) -> Result<Vec<String>> {
-        let sections: Option<Vec<String>> = self.con.lock().await.get("sections").await?;
+        let sections: Option<Vec<String>> = self.con.get().await?.get("sections").await?;
         Ok(sections.unwrap_or(vec![
             "40+ million".to_string(),
             "30+ million".to_string(),
@@ -74,33 +93,162 @@ impl RedisHelper {
 
     pub async fn send_delta(&self, delta: &SubredditDelta) -> Result<()> {
         if delta.prev_state != SubredditState::UNKNOWN || (delta.prev_state == SubredditState::UNKNOWN && delta.subreddit.state == SubredditState::PRIVATE) {
+            let wait_start = std::time::Instant::now();
             self.limiter.until_ready_with_jitter(Jitter::up_to(Duration::from_millis(10))).await;
+            observe_limiter_wait(wait_start.elapsed());
             info!("Sending subreddit delta for {}...", delta.subreddit.name);
-            self.con.lock().await.publish("subreddit_updates", serde_json::to_string(&delta)?).await?;
+            self.con.get().await?.publish("subreddit_updates", serde_json::to_string(&delta)?).await?;
+            DELTAS_SENT.inc();
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(StateTransition {
+                    subreddit: delta.subreddit.name.clone(),
+                    prev_state: delta.prev_state,
+                    new_state: delta.subreddit.state,
+                    section: delta.subreddit.section.clone(),
+                }).await;
+            }
         } else {
             info!("Skipping subreddit delta for {}.", delta.subreddit.name);
+            DELTAS_SKIPPED.inc();
         }
         Ok(())
     }
 
     pub async fn apply_delta(&self, delta: &SubredditDelta) -> Result<()> {
-        self.update_subreddit(&delta.subreddit).await?;
-        if delta.prev_state != delta.subreddit.state {
-            self.send_delta(&delta).await?;
+        let new_json = serde_json::to_string(&delta.subreddit)?;
+        let new_state_str = state_to_str(delta.subreddit.state);
+
+        let old_state_str: String = self.apply_delta_script
+            .key("subreddit")
+            .arg(delta.subreddit.safe_name())
+            .arg(new_json)
+            .arg(new_state_str)
+            .invoke_async(&mut self.con.get().await?)
+            .await?;
+
+        if old_state_str == "UNCHANGED" {
+            DELTAS_SKIPPED.inc();
+            return Ok(());
         }
-        Ok(())
+
+        let prev_state = str_to_state(&old_state_str);
+        let actual_delta = SubredditDelta {
+            subreddit: delta.subreddit.clone(),
+            prev_state,
+        };
+        self.send_delta(&actual_delta).await
+    }
+}
+
+// Renders a SubredditState the same way serde would, since the Lua script
+// compares this against the `state` field of the stored JSON verbatim.
+fn state_to_str(state: SubredditState) -> String {
+    serde_json::to_value(state)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| "NONE".to_string())
+}
+
+fn str_to_state(s: &str) -> SubredditState {
+    if s == "NONE" {
+        return SubredditState::UNKNOWN;
     }
+    serde_json::from_value(serde_json::Value::String(s.to_string())).unwrap_or(SubredditState::UNKNOWN)
 }
 
 pub async fn new_delta_stream(cli: &Cli) -> Result<impl TryStream<Ok = SubredditDelta, Error = anyhow::Error>> {
+    let (tx, rx) = mpsc::channel(cli.delta_stream_buffer_size);
+    let cli = cli.clone();
+    tokio::spawn(supervise_delta_stream(cli, tx));
+    Ok(ReceiverStream::new(rx).map(anyhow::Ok))
+}
+
+// What subscribe_once ran into, so the supervisor knows whether to reset
+// its backoff and whether to keep retrying at all.
+enum SubscribeOutcome {
+    ReceiverClosed,
+    ConnectionLost { received_any: bool },
+}
+
+async fn supervise_delta_stream(cli: Cli, tx: mpsc::Sender<SubredditDelta>) {
+    let mut backoff = INITIAL_RECONNECT_BACKOFF;
+    loop {
+        match subscribe_once(&cli, &tx).await {
+            Ok(SubscribeOutcome::ReceiverClosed) => return,
+            Ok(SubscribeOutcome::ConnectionLost { received_any }) => {
+                if received_any {
+                    // The connection was healthy for a while; don't make it pay
+                    // the accumulated backoff from earlier, unrelated failures.
+                    backoff = INITIAL_RECONNECT_BACKOFF;
+                }
+                warn!("Delta stream pubsub connection lost, reconnecting in {backoff:?}");
+            }
+            Err(err) => {
+                warn!("Failed to (re)subscribe to delta stream ({err}), retrying in {backoff:?}");
+            }
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+    }
+}
+
+async fn subscribe_once(cli: &Cli, tx: &mpsc::Sender<SubredditDelta>) -> Result<SubscribeOutcome> {
     let mut pubsub = cli.new_redis_pubsub().await?;
     pubsub.subscribe("subreddit_updates").await?;
-    let s = pubsub.into_on_message();
-    let s = s.map(|item: Msg| {
+    let mut stream = pubsub.into_on_message();
+
+    let mut received_any = false;
+    while let Some(item) = stream.next().await {
         let item: Msg = item;
-        let delta: String = item.get_payload()?;
-        let delta: SubredditDelta = serde_json::from_str(&delta)?;
-        anyhow::Ok(delta)
-    });
-    Ok(s)
+        let payload: String = match item.get_payload() {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!("Dropping unreadable delta stream payload: {err}");
+                continue;
+            }
+        };
+        let delta: SubredditDelta = match serde_json::from_str(&payload) {
+            Ok(delta) => delta,
+            Err(err) => {
+                warn!("Dropping malformed delta stream message: {err}");
+                continue;
+            }
+        };
+        received_any = true;
+
+        match tx.try_send(delta) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                DELTAS_STREAM_DROPPED.inc();
+                warn!("Delta stream buffer saturated, dropping a message");
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                return Ok(SubscribeOutcome::ReceiverClosed)
+            }
+        }
+    }
+
+    Ok(SubscribeOutcome::ConnectionLost { received_any })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_str_roundtrips() {
+        for state in [
+            SubredditState::PUBLIC,
+            SubredditState::PRIVATE,
+            SubredditState::RESTRICTED,
+            SubredditState::UNKNOWN,
+        ] {
+            assert_eq!(str_to_state(&state_to_str(state)), state);
+        }
+    }
+
+    #[test]
+    fn none_sentinel_maps_to_unknown() {
+        assert_eq!(str_to_state("NONE"), SubredditState::UNKNOWN);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use roux::Reddit;
+use roux::subreddit::Subreddit as RouxSubreddit;
+use tracing::warn;
+
+use crate::cli::StateBackend;
+use crate::reddit::{Subreddit, SubredditDelta, SubredditState};
+use crate::redis_helper::RedisHelper;
+use crate::Cli;
+
+// Authenticated alternative to scraping; distinguishes RESTRICTED from
+// PRIVATE via subreddit_type, which the unauthenticated scraper can't see.
+pub struct RouxStateSource {
+    client: Reddit,
+}
+
+impl RouxStateSource {
+    // `Ok(None)` when no roux credentials were supplied, so callers can fall
+    // back to the scraper without treating it as an error.
+    pub async fn from_cli(cli: &Cli) -> Result<Option<Self>> {
+        let (Some(client_id), Some(client_secret)) =
+            (&cli.roux_client_id, &cli.roux_client_secret)
+        else {
+            return Ok(None);
+        };
+
+        let client = Reddit::new("reddark-remix/roux", client_id, client_secret)
+            .username(cli.roux_username.as_deref().unwrap_or_default())
+            .password(cli.roux_password.as_deref().unwrap_or_default())
+            .login()
+            .await
+            .context("failed to authenticate roux Reddit client")?;
+
+        Ok(Some(Self { client }))
+    }
+
+    pub async fn get_state(&self, name: &str) -> Result<SubredditState> {
+        let subreddit = RouxSubreddit::new_oauth(name, &self.client.client, &self.client.config);
+        let about = subreddit.about().await.context("roux about() call failed")?;
+
+        let state = match about.data.subreddit_type.as_deref() {
+            Some("public") => SubredditState::PUBLIC,
+            Some("restricted") => SubredditState::RESTRICTED,
+            Some("private") => SubredditState::PRIVATE,
+            Some(other) => {
+                warn!("Unrecognized roux subreddit_type '{other}' for r/{name}");
+                SubredditState::UNKNOWN
+            }
+            None => SubredditState::UNKNOWN,
+        };
+        Ok(state)
+    }
+}
+
+// Single entry point scanner workers should call per subreddit: picks the
+// backend named by `--state-backend` and applies the result through the
+// same RedisHelper::apply_delta pipeline regardless of which one ran.
+//
+// `scraper_state` is whatever the caller's existing scrape path already
+// found for this subreddit; it's only consulted for the Scraper backend, so
+// callers running with `--state-backend roux` can skip scraping entirely.
+pub async fn scan_subreddit(
+    cli: &Cli,
+    redis: &RedisHelper,
+    roux: Option<&RouxStateSource>,
+    mut subreddit: Subreddit,
+    scraper_state: SubredditState,
+) -> Result<()> {
+    let prev_state = subreddit.state;
+    subreddit.state = match (cli.state_backend, roux) {
+        (StateBackend::Roux, Some(roux)) => roux.get_state(&subreddit.name).await?,
+        (StateBackend::Roux, None) => anyhow::bail!(
+            "--state-backend roux requires --roux-client-id and --roux-client-secret"
+        ),
+        (StateBackend::Scraper, _) => scraper_state,
+    };
+    redis
+        .apply_delta(&SubredditDelta {
+            subreddit,
+            prev_state,
+        })
+        .await
+}